@@ -15,7 +15,25 @@ pub struct ExecutableBuffer {
     // length of the buffer that has actually been written to
     length: usize,
     // backing buffer
-    buffer: Option<Mmap>
+    buffer: Option<ExecBacking>
+}
+
+/// The backing storage of an `ExecutableBuffer`: a plain anonymous mapping that gets
+/// relocated wholesale when it needs to grow, a `with_reserved`/`new_near` region that was
+/// reserved up front and only needs new pages committed into it as it grows, or the read/execute
+/// half of a `new_dual_mapping` pair.
+#[cfg(not(feature = "vec_memory"))]
+#[derive(Debug)]
+enum ExecBacking {
+    Mmap(Mmap),
+    Reserved(reserved::ReservedBuffer),
+    Dual(std::sync::Arc<dual_mapping::DualMapping>)
+}
+
+#[cfg(feature = "vec_memory")]
+#[derive(Debug)]
+enum ExecBacking {
+    Mmap(Mmap)
 }
 
 /// ExecutableBuffer equivalent that holds a buffer of mutable memory instead of executable memory. It also derefs to a `&mut [u8]`.
@@ -25,7 +43,57 @@ pub struct MutableBuffer {
     // length of the buffer that has actually been written to
     length: usize,
     // backing buffer
-    buffer: Option<MmapMut>
+    buffer: Option<MutBacking>
+}
+
+/// The backing storage of a `MutableBuffer`. See `ExecBacking` for why this isn't always a plain mapping.
+#[cfg(not(feature = "vec_memory"))]
+#[derive(Debug)]
+enum MutBacking {
+    Mmap(MmapMut),
+    Reserved(reserved::ReservedBuffer),
+    Dual(std::sync::Arc<dual_mapping::DualMapping>)
+}
+
+/// The backing storage of a `MutableBuffer` when the `vec_memory` feature is enabled: a
+/// page-aligned heap allocation from the global allocator instead of an anonymous mapping.
+#[cfg(feature = "vec_memory")]
+#[derive(Debug)]
+enum MutBacking {
+    Vec(aligned_buffer::AlignedBuffer)
+}
+
+/// Create a W^X `MutableBuffer`/`ExecutableBuffer` pair backed by a single shared mapping of
+/// `size` bytes, aliased once read/write and once read/execute, so that no page is ever
+/// simultaneously writable and executable. Writes made through the returned `MutableBuffer`
+/// become visible through the returned `ExecutableBuffer` as soon as `MutableBuffer::set_len` is
+/// called to publish them, without either buffer ever needing its protection flipped.
+///
+/// On Linux this is backed by a `memfd_create`'d file mapped twice with `MAP_SHARED`. On
+/// platforms without an equivalent facility this falls back to a plain `MutableBuffer::new`
+/// paired with an empty `ExecutableBuffer`; callers on those platforms should use
+/// `MutableBuffer::make_exec` once writing is done instead of relying on the paired buffer.
+#[cfg(not(feature = "vec_memory"))]
+pub fn new_dual_mapping(size: usize) -> io::Result<(MutableBuffer, ExecutableBuffer)> {
+    if size == 0 {
+        return Ok((MutableBuffer::default(), ExecutableBuffer::default()));
+    }
+
+    match dual_mapping::DualMapping::new(size) {
+        Ok(mapping) => {
+            let mapping = std::sync::Arc::new(mapping);
+            let mutable = MutableBuffer {
+                length: 0,
+                buffer: Some(MutBacking::Dual(mapping.clone()))
+            };
+            let executable = ExecutableBuffer {
+                length: 0,
+                buffer: Some(ExecBacking::Dual(mapping))
+            };
+            Ok((mutable, executable))
+        }
+        Err(_) => Ok((MutableBuffer::new(size)?, ExecutableBuffer::default()))
+    }
 }
 
 impl ExecutableBuffer {
@@ -36,7 +104,8 @@ impl ExecutableBuffer {
     /// buffer. Note that if this buffer is accessed through an Executor, these pointers
     /// will only be valid as long as its lock is held. When no locks are held,
     /// The assembler is free to relocate the executable buffer when it requires
-    /// more memory than available.
+    /// more memory than available, unless it was created through `with_reserved`, in which case
+    /// the base address is stable for the buffer's lifetime.
     pub fn ptr(&self, offset: AssemblyOffset) -> *const u8 {
         &self[offset.0] as *const u8
     }
@@ -47,7 +116,7 @@ impl ExecutableBuffer {
         let buffer = if size == 0 {
             None
         } else {
-            Some(MmapMut::map_anon(size)?.make_exec()?)
+            Some(ExecBacking::Mmap(MmapMut::map_anon(size)?.make_exec()?))
         };
 
         Ok(ExecutableBuffer {
@@ -56,17 +125,100 @@ impl ExecutableBuffer {
         })
     }
 
+    /// Create a new executable buffer that reserves `reserve` bytes of address space up front
+    /// and commits the first `commit` bytes of it as executable memory. Unlike `new`, growing
+    /// this buffer (through `make_mut`/`set_len`/`make_exec`) only commits additional pages
+    /// within the reservation instead of relocating the whole buffer, so pointers returned by
+    /// `ptr` stay valid for as long as the buffer lives and no longer than `reserve` bytes
+    /// are ever written to it.
+    ///
+    /// If the reservation can't be satisfied, for example because no contiguous region of that
+    /// size is available on a 32-bit target, this falls back to the relocating behaviour of
+    /// `new(commit)`.
+    #[cfg(not(feature = "vec_memory"))]
+    pub fn with_reserved(commit: usize, reserve: usize) -> io::Result<ExecutableBuffer> {
+        assert!(commit <= reserve, "cannot commit more than what is reserved");
+
+        if reserve == 0 {
+            return ExecutableBuffer::new(commit);
+        }
+
+        match reserved::ReservedBuffer::new(reserve, commit, true) {
+            Ok(buffer) => Ok(ExecutableBuffer {
+                length: 0,
+                buffer: Some(ExecBacking::Reserved(buffer))
+            }),
+            Err(_) => ExecutableBuffer::new(commit)
+        }
+    }
+
+    /// Create a new executable buffer of `size` bytes, mapped somewhere within `max_distance`
+    /// bytes of `target`. This is for trampolines and hooks that have to reach `target` with an
+    /// architecture-relative branch, e.g. an x86-64 `rel32` displacement or an aarch64 `b`/`bl`
+    /// within ±128 MiB, where the generated code and `target` must live close enough together
+    /// for the relative encoding to hold.
+    ///
+    /// Unlike `new` and `with_reserved`, this has no relocating fallback: if no free region
+    /// within range of `target` can be found, an error is returned instead of silently placing
+    /// the buffer out of range, since a caller relying on in-range branches can't recover from
+    /// that silently.
+    #[cfg(not(feature = "vec_memory"))]
+    pub fn new_near(size: usize, target: usize, max_distance: usize) -> io::Result<ExecutableBuffer> {
+        let buffer = reserved::ReservedBuffer::new_near(size, size, target, max_distance, true)?;
+        Ok(ExecutableBuffer {
+            length: 0,
+            buffer: Some(ExecBacking::Reserved(buffer))
+        })
+    }
+
     /// Query the backing size of this executable buffer
     pub fn size(&self) -> usize {
-        self.buffer.as_ref().map(|b| b.len()).unwrap_or(0) as usize
+        match &self.buffer {
+            None => 0,
+            Some(ExecBacking::Mmap(map)) => map.len(),
+            #[cfg(not(feature = "vec_memory"))]
+            Some(ExecBacking::Reserved(buffer)) => buffer.committed(),
+            #[cfg(not(feature = "vec_memory"))]
+            Some(ExecBacking::Dual(mapping)) => mapping.size()
+        }
     }
 
     /// Change this executable buffer into a mutable buffer.
+    ///
+    /// For a `Dual`-backed buffer obtained from `new_dual_mapping` this just hands back a
+    /// `MutableBuffer` pointing at the same shared RW alias; no protection change is needed
+    /// since that alias was never made executable in the first place.
+    #[cfg(not(feature = "vec_memory"))]
     pub fn make_mut(self) -> io::Result<MutableBuffer> {
-        let buffer = if let Some(map) = self.buffer {
-            Some(map.make_mut()?)
-        } else {
-            None
+        let buffer = match self.buffer {
+            None => None,
+            Some(ExecBacking::Mmap(map)) => Some(MutBacking::Mmap(map.make_mut()?)),
+            Some(ExecBacking::Reserved(mut buffer)) => {
+                buffer.set_exec(false)?;
+                Some(MutBacking::Reserved(buffer))
+            }
+            Some(ExecBacking::Dual(mapping)) => Some(MutBacking::Dual(mapping))
+        };
+
+        Ok(MutableBuffer {
+            length: self.length,
+            buffer
+        })
+    }
+
+    /// Change this executable buffer into a mutable buffer.
+    ///
+    /// The executable mapping is copied into a plain heap allocation and then discarded, so that
+    /// the result holds no executable memory until [`MutableBuffer::make_exec`] is called again.
+    #[cfg(feature = "vec_memory")]
+    pub fn make_mut(self) -> io::Result<MutableBuffer> {
+        let buffer = match self.buffer {
+            None => None,
+            Some(ExecBacking::Mmap(map)) => {
+                let mut vec = aligned_buffer::AlignedBuffer::new(map.len());
+                vec.copy_from_slice(&map);
+                Some(MutBacking::Vec(vec))
+            }
         };
 
         Ok(MutableBuffer {
@@ -76,6 +228,7 @@ impl ExecutableBuffer {
     }
 }
 
+#[cfg(not(feature = "vec_memory"))]
 impl MutableBuffer {
     /// Create a new mutable buffer, backed by a buffer of size `size`.
     /// It will start with an initialized length of 0.
@@ -83,7 +236,7 @@ impl MutableBuffer {
         let buffer = if size == 0 {
             None
         } else {
-            Some(MmapMut::map_anon(size)?)
+            Some(MutBacking::Mmap(MmapMut::map_anon(size)?))
         };
 
         Ok(MutableBuffer {
@@ -94,21 +247,114 @@ impl MutableBuffer {
 
     /// Query the backing size of this mutable buffer
     pub fn size(&self) -> usize {
-        self.buffer.as_ref().map(|b| b.len()).unwrap_or(0) as usize
+        match &self.buffer {
+            None => 0,
+            Some(MutBacking::Mmap(map)) => map.len(),
+            Some(MutBacking::Reserved(buffer)) => buffer.committed(),
+            Some(MutBacking::Dual(mapping)) => mapping.size()
+        }
     }
 
     /// Set the length of the usable part of this mutable buffer. The length
     /// should not be set larger than the allocated size, otherwise methods can panic.
-    pub fn set_len(&mut self, length: usize) {
-        self.length = length
+    ///
+    /// For a buffer created through `ExecutableBuffer::with_reserved`, growing past the
+    /// currently committed size commits additional pages from the reservation; unlike the other
+    /// backends this can fail with a genuine OS error (e.g. the kernel refusing to back the new
+    /// pages), so this returns an `io::Result` rather than panicking on it. For a `Dual`-backed
+    /// buffer this publishes the newly written range to the paired `ExecutableBuffer`'s
+    /// read/execute alias, running it through
+    /// `cache_management::invalidate_icache_lines`/`invalidate_pipeline` first.
+    pub fn set_len(&mut self, length: usize) -> io::Result<()> {
+        match &mut self.buffer {
+            Some(MutBacking::Reserved(buffer)) => {
+                if length > buffer.committed() {
+                    buffer.grow(length)?;
+                }
+            }
+            Some(MutBacking::Dual(mapping)) => mapping.publish(self.length, length),
+            _ => {}
+        }
+        self.length = length;
+        Ok(())
     }
 
     /// Change this mutable buffer into an executable buffer.
+    ///
+    /// For a `Dual`-backed buffer this just hands back an `ExecutableBuffer` pointing at the
+    /// same shared RX alias, which was already executable from the moment it was created.
     pub fn make_exec(self) -> io::Result<ExecutableBuffer> {
-        let buffer = if let Some(map) = self.buffer {
-            Some(map.make_exec()?)
-        } else {
+        let buffer = match self.buffer {
+            None => None,
+            Some(MutBacking::Mmap(map)) => Some(ExecBacking::Mmap(map.make_exec()?)),
+            Some(MutBacking::Reserved(mut buffer)) => {
+                buffer.set_exec(true)?;
+                Some(ExecBacking::Reserved(buffer))
+            }
+            Some(MutBacking::Dual(mapping)) => Some(ExecBacking::Dual(mapping))
+        };
+
+        Ok(ExecutableBuffer {
+            length: self.length,
+            buffer
+        })
+    }
+}
+
+#[cfg(feature = "vec_memory")]
+impl MutableBuffer {
+    /// Create a new mutable buffer, backed by a buffer of size `size`.
+    /// It will start with an initialized length of 0.
+    pub fn new(size: usize) -> io::Result<MutableBuffer> {
+        let buffer = if size == 0 {
             None
+        } else {
+            Some(MutBacking::Vec(aligned_buffer::AlignedBuffer::new(size)))
+        };
+
+        Ok(MutableBuffer {
+            length: 0,
+            buffer
+        })
+    }
+
+    /// Query the backing size of this mutable buffer
+    pub fn size(&self) -> usize {
+        match &self.buffer {
+            None => 0,
+            Some(MutBacking::Vec(vec)) => vec.len()
+        }
+    }
+
+    /// Set the length of the usable part of this mutable buffer. The length
+    /// should not be set larger than the allocated size, otherwise methods can panic.
+    ///
+    /// Always succeeds: a `Vec`-backed buffer has no separate reservation/commit step to fail.
+    pub fn set_len(&mut self, length: usize) -> io::Result<()> {
+        self.length = length;
+        Ok(())
+    }
+
+    /// Change this mutable buffer into an executable buffer.
+    ///
+    /// A plain heap allocation can't be marked executable in general: the allocator gives no
+    /// guarantee that its pages aren't shared with unrelated allocations, so this always copies
+    /// the written bytes into a real anonymous executable mapping rather than attempting to
+    /// reuse the backing allocation in place. As with the other write-then-execute paths
+    /// (`ReservedBuffer::set_exec`, `DualMapping::publish`), the copied range is run through
+    /// `cache_management::invalidate_icache_lines` followed by `invalidate_pipeline` before being
+    /// handed back, since it is freshly written memory the instruction cache and pipeline may not
+    /// have observed yet.
+    pub fn make_exec(self) -> io::Result<ExecutableBuffer> {
+        let buffer = match self.buffer {
+            None => None,
+            Some(MutBacking::Vec(vec)) => {
+                let mut map = MmapMut::map_anon(vec.len())?;
+                map[..self.length].copy_from_slice(&vec[..self.length]);
+                cache_management::invalidate_icache_lines(&map[..self.length]);
+                cache_management::invalidate_pipeline();
+                Some(ExecBacking::Mmap(map.make_exec()?))
+            }
         };
 
         Ok(ExecutableBuffer {
@@ -139,10 +385,16 @@ impl Default for MutableBuffer {
 impl Deref for ExecutableBuffer {
     type Target = [u8];
     fn deref(&self) -> &[u8] {
-        if let Some(map) = &self.buffer {
-            &map[..self.length]
-        } else {
-            &[]
+        match &self.buffer {
+            None => &[],
+            Some(ExecBacking::Mmap(map)) => &map[..self.length],
+            #[cfg(not(feature = "vec_memory"))]
+            Some(ExecBacking::Reserved(buffer)) => &buffer.as_slice()[..self.length],
+            // a `Dual` buffer's usable length tracks what the paired `MutableBuffer` has
+            // published rather than `self.length`, since writes can arrive after this buffer
+            // was handed out.
+            #[cfg(not(feature = "vec_memory"))]
+            Some(ExecBacking::Dual(mapping)) => &mapping.as_rx_slice()[..mapping.published()]
         }
     }
 }
@@ -150,24 +402,549 @@ impl Deref for ExecutableBuffer {
 impl Deref for MutableBuffer {
     type Target = [u8];
     fn deref(&self) -> &[u8] {
-        if let Some(map) = &self.buffer {
-            &map[..self.length]
-        } else {
-            &[]
+        match &self.buffer {
+            None => &[],
+            #[cfg(not(feature = "vec_memory"))]
+            Some(MutBacking::Mmap(map)) => &map[..self.length],
+            #[cfg(not(feature = "vec_memory"))]
+            Some(MutBacking::Reserved(buffer)) => &buffer.as_slice()[..self.length],
+            #[cfg(not(feature = "vec_memory"))]
+            Some(MutBacking::Dual(mapping)) => &mapping.as_rw_slice()[..self.length],
+            #[cfg(feature = "vec_memory")]
+            Some(MutBacking::Vec(vec)) => &vec[..self.length]
         }
     }
 }
 
 impl DerefMut for MutableBuffer {
     fn deref_mut(&mut self) -> &mut [u8] {
-        if let Some(map) = &mut self.buffer {
-            &mut map[..self.length]
-        } else {
-            &mut []
+        let length = self.length;
+        match &mut self.buffer {
+            None => &mut [],
+            #[cfg(not(feature = "vec_memory"))]
+            Some(MutBacking::Mmap(map)) => &mut map[..length],
+            #[cfg(not(feature = "vec_memory"))]
+            Some(MutBacking::Reserved(buffer)) => &mut buffer.as_mut_slice()[..length],
+            #[cfg(not(feature = "vec_memory"))]
+            Some(MutBacking::Dual(mapping)) => &mut mapping.as_rw_mut_slice()[..length],
+            #[cfg(feature = "vec_memory")]
+            Some(MutBacking::Vec(vec)) => &mut vec[..length]
+        }
+    }
+}
+
+/// Backing allocation used by `MutableBuffer` when the `vec_memory` feature is enabled.
+#[cfg(feature = "vec_memory")]
+mod aligned_buffer {
+    use std::alloc::{alloc, dealloc, handle_alloc_error, Layout};
+    use std::ops::{Deref, DerefMut};
+    use std::ptr::NonNull;
+
+    // Large enough to match common page sizes, so a buffer allocated through this path still
+    // hands out pointers that are validly aligned for callers that assume page-aligned code,
+    // even though the allocation itself is never mapped.
+    const ALIGNMENT: usize = 4096;
+
+    /// A fixed-size heap allocation aligned to `ALIGNMENT`. `Vec<u8>` cannot give this guarantee
+    /// and may reallocate on growth, which would invalidate pointers handed out by `ptr()`.
+    #[derive(Debug)]
+    pub struct AlignedBuffer {
+        ptr: NonNull<u8>,
+        len: usize
+    }
+
+    impl AlignedBuffer {
+        pub fn new(len: usize) -> AlignedBuffer {
+            let layout = Layout::from_size_align(len, ALIGNMENT).unwrap();
+            let ptr = unsafe { alloc(layout) };
+            let ptr = match NonNull::new(ptr) {
+                Some(ptr) => ptr,
+                None => handle_alloc_error(layout)
+            };
+            AlignedBuffer { ptr, len }
+        }
+
+        pub fn len(&self) -> usize {
+            self.len
+        }
+    }
+
+    impl Deref for AlignedBuffer {
+        type Target = [u8];
+        fn deref(&self) -> &[u8] {
+            unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+        }
+    }
+
+    impl DerefMut for AlignedBuffer {
+        fn deref_mut(&mut self) -> &mut [u8] {
+            unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+        }
+    }
+
+    impl Drop for AlignedBuffer {
+        fn drop(&mut self) {
+            if self.len > 0 {
+                let layout = Layout::from_size_align(self.len, ALIGNMENT).unwrap();
+                unsafe { dealloc(self.ptr.as_ptr(), layout) };
+            }
+        }
+    }
+
+    // SAFETY: this is a uniquely owned heap allocation, same as the `Vec<u8>` it replaces.
+    unsafe impl Send for AlignedBuffer {}
+    unsafe impl Sync for AlignedBuffer {}
+}
+
+/// Backing storage for `ExecutableBuffer`/`MutableBuffer` created through `with_reserved`: a
+/// virtual address range reserved in one call, with pages committed into it on demand as the
+/// buffer grows, so the base address never moves for the lifetime of the buffer.
+#[cfg(not(feature = "vec_memory"))]
+mod reserved {
+    use std::io;
+    use super::cache_management;
+
+    /// A candidate base address is only usable if its *entire* span (not just the base) stays
+    /// within `max_distance` of `target`, otherwise code emitted near the end of the buffer could
+    /// still land out of relative-branch range. Shared between the Unix and Windows `reserve_near`
+    /// implementations so their range check can't drift apart.
+    fn in_range(candidate: usize, target: usize, size: usize, max_distance: usize) -> bool {
+        candidate.abs_diff(target) <= max_distance && candidate.saturating_add(size).abs_diff(target) <= max_distance
+    }
+
+    #[cfg(unix)]
+    mod sys {
+        use std::io;
+        use std::os::raw::c_void;
+
+        /// Reserve `size` bytes of address space with no access permissions and no backing pages.
+        pub unsafe fn reserve(size: usize) -> io::Result<*mut u8> {
+            let ptr = libc::mmap(std::ptr::null_mut(), size, libc::PROT_NONE, libc::MAP_PRIVATE | libc::MAP_ANON, -1, 0);
+            if ptr == libc::MAP_FAILED {
+                Err(io::Error::last_os_error())
+            } else {
+                Ok(ptr as *mut u8)
+            }
+        }
+
+        /// Commit `size` bytes starting at `ptr`, which must fall within a previous `reserve`
+        /// call, backing them with real pages mapped read/execute or read/write.
+        pub unsafe fn commit(ptr: *mut u8, size: usize, executable: bool) -> io::Result<()> {
+            protect(ptr, size, executable)
+        }
+
+        /// Change the protection of an already-committed range between read/write and read/execute.
+        pub unsafe fn protect(ptr: *mut u8, size: usize, executable: bool) -> io::Result<()> {
+            let prot = if executable { libc::PROT_READ | libc::PROT_EXEC } else { libc::PROT_READ | libc::PROT_WRITE };
+            if libc::mprotect(ptr as *mut c_void, size, prot) != 0 {
+                Err(io::Error::last_os_error())
+            } else {
+                Ok(())
+            }
+        }
+
+        /// Release the entire reservation made by `reserve`.
+        pub unsafe fn release(ptr: *mut u8, size: usize) {
+            libc::munmap(ptr as *mut c_void, size);
+        }
+
+        /// The granularity at which `commit`/`protect` addresses must be aligned.
+        pub fn page_size() -> usize {
+            unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+        }
+
+        /// Find and reserve `size` bytes of free address space within `max_distance` bytes of
+        /// `target`, by walking candidate page-aligned addresses outward from it and attempting
+        /// a `MAP_FIXED_NOREPLACE` mapping at each one, so an already-occupied region is skipped
+        /// instead of silently overwritten.
+        #[cfg(target_os = "linux")]
+        pub unsafe fn reserve_near(target: usize, size: usize, max_distance: usize) -> io::Result<*mut u8> {
+            let page_size = libc::sysconf(libc::_SC_PAGESIZE) as usize;
+            let aligned_target = target & !(page_size - 1);
+
+            let mut offset = 0usize;
+            loop {
+                let candidates: &[usize] = if offset == 0 {
+                    &[aligned_target]
+                } else {
+                    &[aligned_target.saturating_sub(offset), aligned_target.saturating_add(offset)]
+                };
+
+                for &candidate in candidates {
+                    if !super::in_range(candidate, target, size, max_distance) {
+                        continue;
+                    }
+
+                    let ptr = libc::mmap(
+                        candidate as *mut c_void,
+                        size,
+                        libc::PROT_NONE,
+                        libc::MAP_PRIVATE | libc::MAP_ANON | libc::MAP_FIXED_NOREPLACE,
+                        -1,
+                        0
+                    );
+                    if ptr == libc::MAP_FAILED {
+                        continue;
+                    }
+
+                    // Kernels older than 4.17 silently ignore MAP_FIXED_NOREPLACE and treat it as
+                    // a hint, so `mmap` can "succeed" at a kernel-chosen address outside the range
+                    // we asked for. Reject that rather than handing back an out-of-range buffer.
+                    if super::in_range(ptr as usize, target, size, max_distance) {
+                        return Ok(ptr as *mut u8);
+                    } else {
+                        libc::munmap(ptr, size);
+                        return Err(io::Error::new(io::ErrorKind::Other, "the kernel placed the mapping outside of max_distance of the target (MAP_FIXED_NOREPLACE may be unsupported on this kernel)"));
+                    }
+                }
+
+                if offset >= max_distance {
+                    return Err(io::Error::new(io::ErrorKind::Other, "no free address range was found within max_distance of the target"));
+                }
+                offset += page_size;
+            }
+        }
+
+        /// `MAP_FIXED_NOREPLACE` is Linux-specific; other Unix targets would need to risk
+        /// clobbering an existing mapping with plain `MAP_FIXED` instead, so this is left
+        /// unimplemented there rather than doing that silently.
+        #[cfg(not(target_os = "linux"))]
+        pub unsafe fn reserve_near(_target: usize, _size: usize, _max_distance: usize) -> io::Result<*mut u8> {
+            Err(io::Error::new(io::ErrorKind::Other, "finding a free region near a target address is only implemented on Linux and Windows"))
+        }
+    }
+
+    #[cfg(windows)]
+    mod sys {
+        use std::io;
+        use std::ffi::c_void;
+        use winapi::um::memoryapi::{VirtualAlloc, VirtualFree, VirtualProtect};
+        use winapi::um::winnt::{MEM_RESERVE, MEM_COMMIT, MEM_RELEASE, PAGE_NOACCESS, PAGE_READWRITE, PAGE_EXECUTE_READ};
+        use winapi::um::sysinfoapi::{GetSystemInfo, SYSTEM_INFO};
+
+        /// Reserve `size` bytes of address space with no backing pages.
+        pub unsafe fn reserve(size: usize) -> io::Result<*mut u8> {
+            let ptr = VirtualAlloc(std::ptr::null_mut(), size, MEM_RESERVE, PAGE_NOACCESS);
+            if ptr.is_null() {
+                Err(io::Error::last_os_error())
+            } else {
+                Ok(ptr as *mut u8)
+            }
+        }
+
+        /// Commit `size` bytes starting at `ptr`, which must fall within a previous `reserve`
+        /// call, backing them with real pages mapped read/execute or read/write.
+        pub unsafe fn commit(ptr: *mut u8, size: usize, executable: bool) -> io::Result<()> {
+            let protect = if executable { PAGE_EXECUTE_READ } else { PAGE_READWRITE };
+            let res = VirtualAlloc(ptr as *mut c_void, size, MEM_COMMIT, protect);
+            if res.is_null() {
+                Err(io::Error::last_os_error())
+            } else {
+                Ok(())
+            }
+        }
+
+        /// Change the protection of an already-committed range between read/write and read/execute.
+        pub unsafe fn protect(ptr: *mut u8, size: usize, executable: bool) -> io::Result<()> {
+            let protect = if executable { PAGE_EXECUTE_READ } else { PAGE_READWRITE };
+            let mut old = 0;
+            if VirtualProtect(ptr as *mut c_void, size, protect, &mut old) == 0 {
+                Err(io::Error::last_os_error())
+            } else {
+                Ok(())
+            }
+        }
+
+        /// Release the entire reservation made by `reserve`.
+        pub unsafe fn release(ptr: *mut u8, size: usize) {
+            VirtualFree(ptr as *mut c_void, 0, MEM_RELEASE);
+        }
+
+        /// The granularity at which `commit`/`protect` addresses must be aligned.
+        pub fn page_size() -> usize {
+            let mut info: SYSTEM_INFO = unsafe { std::mem::zeroed() };
+            unsafe { GetSystemInfo(&mut info) };
+            info.dwPageSize as usize
+        }
+
+        /// Find and reserve `size` bytes of free address space within `max_distance` bytes of
+        /// `target`, by querying candidate page-aligned addresses outward from it with
+        /// `VirtualAlloc(MEM_RESERVE)`, which (unlike Unix `MAP_FIXED`) simply fails instead of
+        /// clobbering an existing mapping when the requested address isn't free.
+        pub unsafe fn reserve_near(target: usize, size: usize, max_distance: usize) -> io::Result<*mut u8> {
+            let mut info: SYSTEM_INFO = std::mem::zeroed();
+            GetSystemInfo(&mut info);
+            let granularity = info.dwAllocationGranularity as usize;
+            let aligned_target = target & !(granularity - 1);
+
+            let mut offset = 0usize;
+            loop {
+                let candidates: &[usize] = if offset == 0 {
+                    &[aligned_target]
+                } else {
+                    &[aligned_target.saturating_sub(offset), aligned_target.saturating_add(offset)]
+                };
+
+                for &candidate in candidates {
+                    if !super::in_range(candidate, target, size, max_distance) {
+                        continue;
+                    }
+
+                    let ptr = VirtualAlloc(candidate as *mut c_void, size, MEM_RESERVE, PAGE_NOACCESS);
+                    if !ptr.is_null() {
+                        return Ok(ptr as *mut u8);
+                    }
+                }
+
+                if offset >= max_distance {
+                    return Err(io::Error::new(io::ErrorKind::Other, "no free address range was found within max_distance of the target"));
+                }
+                offset += granularity;
+            }
+        }
+    }
+
+    /// A reservation of `reserve` bytes of address space, of which `committed` bytes starting
+    /// from the base are currently backed by real pages, either read/write or read/execute
+    /// depending on `executable`.
+    #[derive(Debug)]
+    pub struct ReservedBuffer {
+        base: *mut u8,
+        reserve: usize,
+        committed: usize,
+        executable: bool
+    }
+
+    // SAFETY: `ReservedBuffer` uniquely owns the mapping it holds, same as `Mmap`/`MmapMut`.
+    unsafe impl Send for ReservedBuffer {}
+    unsafe impl Sync for ReservedBuffer {}
+
+    impl ReservedBuffer {
+        /// Reserve `reserve` bytes of address space without committing any physical pages, then
+        /// commit the first `commit` bytes, mapped executable if `executable` is set and
+        /// read/write otherwise.
+        pub fn new(reserve: usize, commit: usize, executable: bool) -> io::Result<ReservedBuffer> {
+            assert!(commit <= reserve);
+            let base = unsafe { sys::reserve(reserve)? };
+            Self::from_base(base, reserve, commit, executable)
+        }
+
+        /// Reserve `reserve` bytes of address space within `max_distance` bytes of `target`, then
+        /// commit the first `commit` bytes as in `new`. Used for trampolines/hooks that need to
+        /// reach `target` with an architecture-relative displacement.
+        pub fn new_near(reserve: usize, commit: usize, target: usize, max_distance: usize, executable: bool) -> io::Result<ReservedBuffer> {
+            assert!(commit <= reserve);
+            let base = unsafe { sys::reserve_near(target, reserve, max_distance)? };
+            Self::from_base(base, reserve, commit, executable)
+        }
+
+        fn from_base(base: *mut u8, reserve: usize, commit: usize, executable: bool) -> io::Result<ReservedBuffer> {
+            let mut buffer = ReservedBuffer {
+                base,
+                reserve,
+                committed: 0,
+                executable
+            };
+
+            if commit > 0 {
+                if let Err(e) = buffer.grow(commit) {
+                    // the reservation was already made; tear it down before bailing out
+                    drop(buffer);
+                    return Err(e);
+                }
+            }
+
+            Ok(buffer)
+        }
+
+        /// The number of bytes currently committed (and readable/writable or readable/executable).
+        pub fn committed(&self) -> usize {
+            self.committed
+        }
+
+        pub fn as_slice(&self) -> &[u8] {
+            unsafe { std::slice::from_raw_parts(self.base, self.committed) }
+        }
+
+        pub fn as_mut_slice(&mut self) -> &mut [u8] {
+            unsafe { std::slice::from_raw_parts_mut(self.base, self.committed) }
+        }
+
+        /// Grow the committed region to at least `len` bytes, committing the newly covered pages
+        /// with the buffer's current protection. Returns an error (without panicking) if `len`
+        /// exceeds the reservation or the commit fails.
+        pub fn grow(&mut self, len: usize) -> io::Result<()> {
+            if len <= self.committed {
+                return Ok(());
+            }
+
+            if len > self.reserve {
+                return Err(io::Error::new(io::ErrorKind::Other, "requested length exceeds the reserved address range"));
+            }
+
+            // `committed` is a raw byte count, not necessarily a page boundary (`set_len` is
+            // called with arbitrary instruction-byte lengths), but `commit` ends up at `mprotect`/
+            // `VirtualAlloc`, which require a page-aligned address. Re-commit from the start of the
+            // partially-committed page rather than from `committed` itself.
+            let page_size = sys::page_size();
+            let aligned_committed = self.committed & !(page_size - 1);
+
+            unsafe {
+                let new_base = self.base.add(aligned_committed);
+                sys::commit(new_base, len - aligned_committed, self.executable)?;
+            }
+
+            self.committed = len;
+            Ok(())
+        }
+
+        /// Flip the protection of the already-committed pages between read/write and
+        /// read/execute. A flip from read/write to read/execute is followed by an icache
+        /// invalidation and pipeline flush over the committed range (no-ops on architectures with
+        /// a coherent instruction cache), since code just written while the pages were read/write
+        /// needs to be made visible before it is jumped to.
+        pub fn set_exec(&mut self, executable: bool) -> io::Result<()> {
+            if self.committed > 0 {
+                unsafe { sys::protect(self.base, self.committed, executable)?; }
+            }
+
+            if executable && !self.executable {
+                cache_management::invalidate_icache_lines(self.as_slice());
+                cache_management::invalidate_pipeline();
+            }
+
+            self.executable = executable;
+            Ok(())
+        }
+    }
+
+    impl Drop for ReservedBuffer {
+        fn drop(&mut self) {
+            unsafe { sys::release(self.base, self.reserve) }
         }
     }
 }
 
+/// Backing storage for the `MutableBuffer`/`ExecutableBuffer` pair returned by
+/// `new_dual_mapping`: a single shared-memory object mapped twice, once read/write and once
+/// read/execute, so neither alias is ever both writable and executable at once.
+#[cfg(not(feature = "vec_memory"))]
+mod dual_mapping {
+    use std::io;
+    use std::fs::File;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use super::cache_management;
+
+    #[derive(Debug)]
+    pub struct DualMapping {
+        // kept alive so the backing pages are only released once both mappings have been torn down
+        _file: File,
+        rw_base: *mut u8,
+        rx_base: *mut u8,
+        size: usize,
+        published: AtomicUsize
+    }
+
+    // SAFETY: the two raw pointers are mappings of a shared file object uniquely owned by this
+    // struct (the `File` is never exposed), so sharing `DualMapping` across threads is sound as
+    // long as callers respect the single-writer convention documented on `as_rw_mut_slice`.
+    unsafe impl Send for DualMapping {}
+    unsafe impl Sync for DualMapping {}
+
+    impl DualMapping {
+        #[cfg(target_os = "linux")]
+        pub fn new(size: usize) -> io::Result<DualMapping> {
+            use std::os::unix::io::FromRawFd;
+            use std::ffi::CStr;
+
+            unsafe {
+                let name = CStr::from_bytes_with_nul(b"dynasm-rs-dual\0").unwrap();
+                let fd = libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC);
+                if fd < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                let file = File::from_raw_fd(fd);
+
+                if libc::ftruncate(fd, size as libc::off_t) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                let rw = libc::mmap(std::ptr::null_mut(), size, libc::PROT_READ | libc::PROT_WRITE, libc::MAP_SHARED, fd, 0);
+                if rw == libc::MAP_FAILED {
+                    return Err(io::Error::last_os_error());
+                }
+
+                let rx = libc::mmap(std::ptr::null_mut(), size, libc::PROT_READ | libc::PROT_EXEC, libc::MAP_SHARED, fd, 0);
+                if rx == libc::MAP_FAILED {
+                    let err = io::Error::last_os_error();
+                    libc::munmap(rw, size);
+                    return Err(err);
+                }
+
+                Ok(DualMapping {
+                    _file: file,
+                    rw_base: rw as *mut u8,
+                    rx_base: rx as *mut u8,
+                    size,
+                    published: AtomicUsize::new(0)
+                })
+            }
+        }
+
+        /// `memfd_create` is Linux-specific; other platforms have no equivalent wired up here, so
+        /// they always fall back to the single-mapping flip.
+        #[cfg(not(target_os = "linux"))]
+        pub fn new(_size: usize) -> io::Result<DualMapping> {
+            Err(io::Error::new(io::ErrorKind::Other, "dual-aliased W^X mappings are only supported on Linux"))
+        }
+
+        pub fn size(&self) -> usize {
+            self.size
+        }
+
+        /// The length of the RW alias that has been published for the RX alias to see.
+        pub fn published(&self) -> usize {
+            self.published.load(Ordering::Acquire)
+        }
+
+        pub fn as_rx_slice(&self) -> &[u8] {
+            unsafe { std::slice::from_raw_parts(self.rx_base, self.size) }
+        }
+
+        pub fn as_rw_slice(&self) -> &[u8] {
+            unsafe { std::slice::from_raw_parts(self.rw_base, self.size) }
+        }
+
+        /// Only the single `MutableBuffer` half of a pair ever calls this, and the paired
+        /// `ExecutableBuffer` only ever reads through the distinct `rx_base` alias, so handing
+        /// out a `&mut` into the RW alias here does not alias a live reference anywhere else.
+        pub fn as_rw_mut_slice(&self) -> &mut [u8] {
+            unsafe { std::slice::from_raw_parts_mut(self.rw_base, self.size) }
+        }
+
+        /// Make the `[old_len, new_len)` range of the RW alias visible through the RX alias:
+        /// invalidate the instruction cache lines it covers and flush the pipeline, then publish
+        /// the new length so `ExecutableBuffer::deref` picks it up.
+        pub fn publish(&self, old_len: usize, new_len: usize) {
+            if new_len > old_len {
+                cache_management::invalidate_icache_lines(&self.as_rw_slice()[old_len..new_len]);
+                cache_management::invalidate_pipeline();
+            }
+            self.published.store(new_len, Ordering::Release);
+        }
+    }
+
+    impl Drop for DualMapping {
+        fn drop(&mut self) {
+            // a `DualMapping` is only ever constructed on Linux, see `new`
+            #[cfg(unix)]
+            unsafe {
+                libc::munmap(self.rw_base as *mut std::ffi::c_void, self.size);
+                libc::munmap(self.rx_base as *mut std::ffi::c_void, self.size);
+            }
+        }
+    }
+}
 
 #[cfg(target_arch="aarch64")]
 pub mod cache_management {
@@ -197,7 +974,7 @@ pub mod cache_management {
     };
 
     #[link_section = ".text"]
-    static INVALIDATE_PIPELINE: Align4<[u8; 12]> = Align4 { 
+    static INVALIDATE_PIPELINE: Align4<[u8; 12]> = Align4 {
         inner: [
             0x9f, 0x3b, 0x03, 0xd5, // dsb ish
             0xdf, 0x3f, 0x03, 0xd5, // isb sy
@@ -253,7 +1030,44 @@ pub mod cache_management {
     }
 }
 
-#[cfg(not(target_arch="aarch64"))]
+#[cfg(any(target_arch = "riscv64", target_arch = "riscv32"))]
+pub mod cache_management {
+    //! This module exports the necessary interfaces to handle instruction cache invalidation that has to happen on the target platform.
+    //! The current target platform is RISC-V, where self-modified code must be made visible to the issuing hart with an explicit `fence.i`.
+
+    /// RISC-V has no instruction to invalidate individual instruction cache lines from user
+    /// space; on Linux this instead asks the kernel to do it for every hart the code might run
+    /// on, via the `riscv_flush_icache` syscall (flags = 0 broadcasts the flush to the whole
+    /// process's hart set through an IPI), since the local-only `fence.i` issued by
+    /// `invalidate_pipeline` cannot reach harts other than the one that wrote the code.
+    #[cfg(target_os = "linux")]
+    pub fn invalidate_icache_lines(slice: &[u8]) {
+        let start = slice.as_ptr() as usize;
+        let end = start + slice.len();
+        unsafe {
+            libc::syscall(libc::SYS_riscv_flush_icache, start, end, 0usize);
+        }
+    }
+
+    /// No cross-hart coherence facility is wired up outside Linux, so this is a no-op here;
+    /// `invalidate_pipeline`'s `fence.i` still guarantees the issuing hart sees its own writes,
+    /// but a caller that hands the written code to a different hart needs to arrange that hart's
+    /// coherence itself.
+    #[cfg(not(target_os = "linux"))]
+    pub fn invalidate_icache_lines(_slice: &[u8]) {}
+
+    /// Emits `fence.i`, ordering the instruction fetches of the hart that executes it after all
+    /// data writes this hart has completed so far. `fence.i` only synchronizes the issuing hart:
+    /// cross-hart coherence is handled separately by `invalidate_icache_lines` on platforms where
+    /// a facility for it exists.
+    pub fn invalidate_pipeline() {
+        unsafe {
+            std::arch::asm!("fence.i");
+        }
+    }
+}
+
+#[cfg(not(any(target_arch="aarch64", target_arch = "riscv64", target_arch = "riscv32")))]
 pub mod cache_management {
     //! This module exports the necessary interfaces to handle instruction cache invalidation that has to happen on the target platform.
     //! The current target architecture has a coherent instruction cache, data cache and pipeline so these are no-ops.
@@ -262,4 +1076,95 @@ pub mod cache_management {
     pub fn invalidate_icache_lines(_slice: &[u8]) {}
     /// Ensures the instruction pipeline is brought fully up to date with any previous writes and cache invalidations. This is a no-op on the current platform.
     pub fn invalidate_pipeline() {}
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `make_exec` on the `vec_memory` backend has to produce memory that is actually executable,
+    /// not just readable bytes that happen to match; running the written code is the only way to
+    /// check that.
+    #[cfg(all(feature = "vec_memory", target_arch = "x86_64"))]
+    #[test]
+    fn vec_memory_round_trip_executes() {
+        // mov eax, 42; ret
+        const CODE: [u8; 6] = [0xb8, 0x2a, 0x00, 0x00, 0x00, 0xc3];
+
+        let mut buffer = MutableBuffer::new(CODE.len()).unwrap();
+        buffer.set_len(CODE.len()).unwrap();
+        buffer.copy_from_slice(&CODE);
+
+        let exec = buffer.make_exec().unwrap();
+        let f: extern "C" fn() -> u32 = unsafe { std::mem::transmute(exec.ptr(AssemblyOffset(0))) };
+        assert_eq!(f(), 42);
+    }
+
+    /// Growing a `with_reserved` buffer past its first committed page must not relocate it: that
+    /// stability is the entire point of the reservation over the plain relocating `new`.
+    #[cfg(not(feature = "vec_memory"))]
+    #[test]
+    fn with_reserved_ptr_stable_across_growth() {
+        let page = 4096;
+
+        let mut mutable = ExecutableBuffer::with_reserved(page, page * 4).unwrap().make_mut().unwrap();
+        mutable.set_len(1).unwrap();
+        mutable[0] = 0xc3; // ret
+        let exec = mutable.make_exec().unwrap();
+        let base = exec.ptr(AssemblyOffset(0)) as usize;
+
+        // grow well past the first committed page
+        let mut mutable = exec.make_mut().unwrap();
+        mutable.set_len(page * 2).unwrap();
+        let exec = mutable.make_exec().unwrap();
+
+        assert_eq!(exec.ptr(AssemblyOffset(0)) as usize, base);
+    }
+
+    /// Bytes written through the RW alias of a dual mapping must become visible through the RX
+    /// alias once `set_len` publishes them, without either alias ever needing a protection flip.
+    #[cfg(not(feature = "vec_memory"))]
+    #[test]
+    fn dual_mapping_publish_is_visible_on_rx_alias() {
+        let (mut mutable, executable) = new_dual_mapping(4096).unwrap();
+        if executable.size() == 0 {
+            // the current platform/kernel has no memfd-backed dual mapping support, so
+            // `new_dual_mapping` fell back to a plain buffer pair; nothing to verify here.
+            return;
+        }
+
+        mutable.set_len(1).unwrap();
+        mutable[0] = 0xc3; // ret
+
+        assert_eq!(&executable[..1], &[0xc3]);
+    }
+
+    /// `new_near` must only ever hand back a buffer whose whole span, not just its base address,
+    /// fits within `max_distance` of the target.
+    #[cfg(not(feature = "vec_memory"))]
+    #[test]
+    fn new_near_stays_within_max_distance() {
+        let marker = 0u8;
+        let target = &marker as *const u8 as usize;
+        let max_distance = 256 * 1024 * 1024;
+        let size = 4096;
+
+        match ExecutableBuffer::new_near(size, target, max_distance) {
+            Ok(buffer) => {
+                let mut mutable = buffer.make_mut().unwrap();
+                mutable.set_len(1).unwrap();
+                mutable[0] = 0xc3; // ret
+                let buffer = mutable.make_exec().unwrap();
+
+                let base = buffer.ptr(AssemblyOffset(0)) as usize;
+                let end = base + size;
+                assert!(base.abs_diff(target) <= max_distance);
+                assert!(end.abs_diff(target) <= max_distance);
+            }
+            Err(_) => {
+                // `reserve_near` has no implementation on Unix targets other than Linux; a clear
+                // error instead of an out-of-range buffer is exactly the contract under test.
+            }
+        }
+    }
+}